@@ -2,7 +2,9 @@ use crate::util::{ObjectReference, VMWorkerThread};
 use crate::vm::{RootsWorkFactory, Scanning, SlotVisitor, VMBinding};
 use core::marker::Send;
 use crossbeam::queue::SegQueue;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 pub fn check_gc<Pred, VM>(pred: Pred, tls: VMWorkerThread)
@@ -14,18 +16,98 @@ where
     checker.check_roots();
     let msg = checker.worker.errors.lock().expect("uh oh");
     if !msg.is_empty() {
-        for message in msg.iter() {
-            println!("{:?}", message);
-        }
+        report_errors(&msg);
+        panic!("things went poorly");
+    }
+}
+
+/// Run [`SanityChecker`] with `num_workers` threads draining the shared work
+/// list concurrently, instead of a single thread. Behaviorally equivalent to
+/// [`check_gc`]; only the traversal is parallelized.
+pub fn check_gc_parallel<Pred, VM>(pred: Pred, tls: VMWorkerThread, num_workers: usize)
+where
+    VM: VMBinding + ConservativeScanning + std::fmt::Debug,
+    Pred: ValidityPredicate<VM> + Send + Clone + 'static,
+{
+    let mut checker: SanityChecker<Pred, VM> = SanityChecker::new(pred, tls);
+    checker.check_roots_parallel(num_workers);
+    let msg = checker.worker.errors.lock().expect("uh oh");
+    if !msg.is_empty() {
+        report_errors(&msg);
         panic!("things went poorly");
     }
 }
 
+/// Print every sanity-check error along with the chain of (object, slot)
+/// hops that led from a GC root down to the offending object or slot, so a
+/// corrupt heap can actually be diagnosed instead of just named.
+fn report_errors<VM: VMBinding + std::fmt::Debug>(errors: &[Error<VM>]) {
+    for error in errors {
+        match error {
+            Error::BadNode(node, msg, path) => {
+                println!("bad node {:?}: {}", node, msg);
+                print_path::<VM>(path, PathEnd::Node(*node));
+            }
+            Error::BadEdge(slot, msg, path) => {
+                println!("bad edge {:?}: {}", slot, msg);
+                print_path::<VM>(path, PathEnd::Invalid);
+            }
+        }
+    }
+}
+
+/// A chain of (object, slot) hops from a GC root down to some object,
+/// reconstructed by [`WorkFactory::path_to`].
+type Path<VM> = Vec<(ObjectReference, <VM as VMBinding>::VMSlot)>;
+
+/// For every object discovered by a [`WorkFactory`], the (object, slot) it
+/// was discovered through, or `None` if it came straight from a GC root.
+type ParentMap<VM> = HashMap<ObjectReference, Option<(ObjectReference, <VM as VMBinding>::VMSlot)>>;
+
+/// What a reconstructed path terminates at. For [`Error::BadNode`] the
+/// chain ends at the bad object itself, reached via the last entry's slot.
+/// For [`Error::BadEdge`] the bad slot is already the last entry in `path`
+/// (see `push_slot`), so the chain just ends without resolving to an
+/// object, since the slot is what's invalid.
+enum PathEnd {
+    Node(ObjectReference),
+    Invalid,
+}
+
+/// Each entry in `path` is `(owner, slot)`: the object that owns `slot`,
+/// and the slot in that object pointing at the *next* hop (the next
+/// entry's owner, or `end` for the last entry). The root's own slot into
+/// the first owner isn't recorded (root slots have no parent object, see
+/// `push_slot`), so the chain is printed as starting from an unrecorded
+/// root slot rather than from the root itself.
+fn print_path<VM: VMBinding + std::fmt::Debug>(
+    path: &[(ObjectReference, <VM as VMBinding>::VMSlot)],
+    end: PathEnd,
+) {
+    if path.is_empty() {
+        print!("  reached directly from a GC root ->");
+    } else {
+        print!("  reached via an unrecorded root slot");
+        for (obj, slot) in path {
+            print!(" -> {:?} -[{:?}]->", obj, slot);
+        }
+    }
+    match end {
+        PathEnd::Node(node) => println!(" {:?} (bad)", node),
+        PathEnd::Invalid => println!(" (invalid)"),
+    }
+}
+
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
 enum Error<VM: VMBinding> {
-    BadEdge(<VM as VMBinding>::VMSlot, String),
-    BadNode(ObjectReference, String),
+    /// A bad slot, the reason it's invalid, and the chain of (object, slot)
+    /// hops from a GC root down to the object that owns this slot, ending
+    /// with that object and this slot itself.
+    BadEdge(<VM as VMBinding>::VMSlot, String, Path<VM>),
+    /// A bad object, the reason it's invalid, and the chain of (object,
+    /// slot) hops that were followed from a GC root down to this object.
+    BadNode(ObjectReference, String, Path<VM>),
 }
 
 pub trait ConservativeScanning: VMBinding {
@@ -42,12 +124,62 @@ pub trait ValidityPredicate<VM: VMBinding> {
     fn is_valid_slot(&self, slot: <VM as VMBinding>::VMSlot) -> Result<(), String>;
 }
 
+////////////////////////////////////////////////////
+// ShardedSet
+////////////////////////////////////////////////////
+
+/// A lock-striped concurrent set of [`ObjectReference`]s. Used in place of a
+/// plain `HashSet` behind a single lock so that a multi-threaded traversal's
+/// visitation check doesn't serialize the whole scan on one mutex.
+struct ShardedSet {
+    shards: Vec<Mutex<HashSet<ObjectReference>>>,
+}
+
+impl ShardedSet {
+    const SHARD_COUNT: usize = 64;
+
+    fn new() -> Self {
+        ShardedSet {
+            shards: (0..Self::SHARD_COUNT)
+                .map(|_| Mutex::new(HashSet::new()))
+                .collect(),
+        }
+    }
+
+    fn shard_for(&self, obj: ObjectReference) -> &Mutex<HashSet<ObjectReference>> {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        obj.hash(&mut hasher);
+        &self.shards[(hasher.finish() as usize) % self.shards.len()]
+    }
+
+    /// Returns `true` the first time `obj` is inserted, `false` on every
+    /// later call, same as `HashSet::insert`.
+    fn insert(&self, obj: ObjectReference) -> bool {
+        self.shard_for(obj)
+            .lock()
+            .expect("shard lock poisoned")
+            .insert(obj)
+    }
+}
+
 ////////////////////////////////////////////////////
 // WorkFactory
 ////////////////////////////////////////////////////
 struct WorkFactory<Pred: ValidityPredicate<VM> + Clone, VM: VMBinding> {
     errors: Arc<Mutex<Vec<Error<VM>>>>,
     work_list: Arc<SegQueue<ObjectReference>>,
+    /// Count of items pushed onto `work_list` but not yet fully processed.
+    /// Only consulted by the parallel traversal in
+    /// [`SanityChecker::check_roots_parallel`] to detect quiescence; the
+    /// serial traversal ignores it and just drains `work_list` to empty.
+    in_flight: Arc<AtomicUsize>,
+    /// Lets [`Self::path_to`] reconstruct the root-to-object chain for a
+    /// sanity-check error without a second scan.
+    parents: Arc<Mutex<ParentMap<VM>>>,
+    /// The object currently being scanned, used as the parent when
+    /// [`Self::push_slot`] discovers a new object through one of its slots.
+    /// `None` while scanning roots, since those have no parent object.
+    current_source: Option<ObjectReference>,
     pred: Pred,
 }
 
@@ -60,6 +192,9 @@ where
         WorkFactory {
             errors: self.errors.clone(),
             work_list: self.work_list.clone(),
+            in_flight: self.in_flight.clone(),
+            parents: self.parents.clone(),
+            current_source: self.current_source,
             pred: self.pred.clone(),
         }
     }
@@ -79,18 +214,69 @@ where
         match self.pred.is_valid_slot(slot) {
             Ok(()) => {
                 let node = slot.load().expect("invalid");
+                let parent = self.current_source.map(|src| (src, slot));
+                self.parents
+                    .lock()
+                    .expect("failed to lock")
+                    .entry(node)
+                    .or_insert(parent);
+                self.in_flight.fetch_add(1, Ordering::SeqCst);
                 self.work_list.push(node);
             }
-            Err(error) => self
-                .errors
-                .lock()
-                .expect("failed to lock")
-                .push(Error::BadEdge(slot, error)),
+            Err(error) => {
+                // The chain up to (but not including) `current_source`,
+                // plus the owning object and the bad slot itself, so the
+                // chain terminates at the actual offending edge instead of
+                // stopping one hop short of it.
+                let mut path = self.path_to_current_source();
+                if let Some(src) = self.current_source {
+                    path.push((src, slot));
+                }
+                self.errors
+                    .lock()
+                    .expect("failed to lock")
+                    .push(Error::BadEdge(slot, error, path));
+            }
         }
     }
 
     fn push_nodes(&mut self, nodes: Vec<ObjectReference>) {
-        nodes.into_iter().for_each(|node| self.work_list.push(node));
+        self.in_flight.fetch_add(nodes.len(), Ordering::SeqCst);
+        let mut parents = self.parents.lock().expect("failed to lock");
+        for node in nodes {
+            parents.entry(node).or_insert(None);
+            self.work_list.push(node);
+        }
+    }
+
+    /// Reconstruct the chain of (object, slot) hops from a GC root down to
+    /// `node`, by walking `parents` backward.
+    fn path_to(&self, mut node: ObjectReference) -> Path<VM> {
+        let parents = self.parents.lock().expect("failed to lock");
+        let mut path = Vec::new();
+        while let Some(Some((parent, slot))) = parents.get(&node) {
+            path.push((*parent, *slot));
+            node = *parent;
+        }
+        path.reverse();
+        path
+    }
+
+    fn path_to_current_source(&self) -> Path<VM> {
+        match self.current_source {
+            Some(obj) => self.path_to(obj),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl<Pred, VM> SlotVisitor<<VM as VMBinding>::VMSlot> for WorkFactory<Pred, VM>
+where
+    VM: VMBinding,
+    Pred: ValidityPredicate<VM> + Send + Clone,
+{
+    fn visit_slot(&mut self, slot: <VM as VMBinding>::VMSlot) {
+        self.push_slot(slot);
     }
 }
 
@@ -126,16 +312,6 @@ pub struct SanityChecker<
     pred: Pred,
 }
 
-impl<Pred, VM> SlotVisitor<<VM as VMBinding>::VMSlot> for SanityChecker<Pred, VM>
-where
-    VM: VMBinding + ConservativeScanning,
-    Pred: ValidityPredicate<VM> + Send + Clone,
-{
-    fn visit_slot(&mut self, slot: <VM as VMBinding>::VMSlot) {
-        self.worker.push_slot(slot);
-    }
-}
-
 impl<Pred, VM> SanityChecker<Pred, VM>
 where
     VM: VMBinding + ConservativeScanning,
@@ -147,6 +323,9 @@ where
         let worker = WorkFactory {
             errors: errors,
             work_list: work_list,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            parents: Arc::new(Mutex::new(HashMap::new())),
+            current_source: None,
             pred: pred.clone(),
         };
         SanityChecker {
@@ -170,14 +349,88 @@ where
         }
     }
 
+    /// Like [`Self::check_roots`], but drains the shared work list with
+    /// `num_workers` threads instead of just the calling one, following the
+    /// `SyncIoEngine`-vs-`AsyncIoEngine` split from external
+    /// thin-provisioning-tools. Each worker pops from the same `SegQueue`,
+    /// scans, and pushes discovered nodes back; a [`ShardedSet`] keeps
+    /// visitation idempotent without one lock serializing the whole scan,
+    /// and `worker.in_flight` lets every worker agree on when the graph has
+    /// been fully explored.
+    pub fn check_roots_parallel(&mut self, num_workers: usize) {
+        <VM::VMScanning as Scanning<VM>>::scan_vm_specific_roots(self.tls, self.worker.clone());
+
+        let visited = Arc::new(ShardedSet::new());
+        let tls = self.tls;
+        let pool = threadpool::ThreadPool::new(num_workers.max(1));
+        for _ in 0..num_workers.max(1) {
+            let mut worker = self.worker.clone();
+            let visited = visited.clone();
+            pool.execute(move || Self::drain_parallel(tls, &mut worker, &visited));
+        }
+        pool.join();
+    }
+
+    fn drain_parallel(tls: VMWorkerThread, worker: &mut WorkFactory<Pred, VM>, visited: &ShardedSet) {
+        struct InFlightGuard(Arc<AtomicUsize>);
+        impl Drop for InFlightGuard {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        let backoff = crossbeam::utils::Backoff::new();
+        loop {
+            match worker.work_list.pop() {
+                Some(node) => {
+                    backoff.reset();
+                    // Release this unit of `in_flight` only once `node` has
+                    // been fully scanned, so no other worker sees zero while
+                    // this one may still push more nodes. The drop guard
+                    // also releases it if scanning panics, so the other
+                    // workers never spin in the `None` branch below forever.
+                    let _done = InFlightGuard(worker.in_flight.clone());
+                    if visited.insert(node) {
+                        match worker.pred.is_valid_node(node) {
+                            Ok(()) => {
+                                worker.current_source = Some(node);
+                                <VM as ConservativeScanning>::conservatively_scan_object(
+                                    tls, node, worker,
+                                );
+                            }
+                            Err(error) => {
+                                let path = worker.path_to(node);
+                                worker.push_error(Error::BadNode(node, error, path));
+                            }
+                        }
+                    }
+                }
+                None => {
+                    if worker.in_flight.load(Ordering::SeqCst) == 0 {
+                        break;
+                    }
+                    backoff.snooze();
+                }
+            }
+        }
+    }
+
     fn check_node(&mut self, node: ObjectReference) {
         if !self.visited.contains(&node) {
             self.visited.insert(node);
             match self.pred.is_valid_node(node) {
                 Ok(()) => {
-                    <VM as ConservativeScanning>::conservatively_scan_object(self.tls, node, self)
+                    self.worker.current_source = Some(node);
+                    <VM as ConservativeScanning>::conservatively_scan_object(
+                        self.tls,
+                        node,
+                        &mut self.worker,
+                    )
+                }
+                Err(error) => {
+                    let path = self.worker.path_to(node);
+                    self.worker.push_error(Error::BadNode(node, error, path));
                 }
-                Err(error) => self.worker.push_error(Error::BadNode(node, error)),
             }
         }
     }
@@ -188,7 +441,7 @@ where
 ////////////////////////////////////////////////////
 
 pub mod graph {
-    use super::{ConservativeScanning, ValidityPredicate};
+    use super::{ConservativeScanning, ShardedSet, ValidityPredicate};
     use crate::vm::slot::Slot;
     use crate::vm::Scanning;
     use crate::{
@@ -196,10 +449,15 @@ pub mod graph {
         vm::{SlotVisitor, VMBinding},
     };
     use crossbeam::queue::SegQueue;
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
     use std::io::Write;
     use std::marker::PhantomData;
-    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Number of records a [`BufferedOutput`] accumulates before flushing to
+    /// its underlying [`GraphOutput`].
+    const DEFAULT_BATCH_SIZE: usize = 4096;
 
     pub fn dump_dot<Pred, VM>(
         pred: Pred,
@@ -211,14 +469,26 @@ pub mod graph {
         VM: VMBinding + ConservativeScanning + NodeAttrs,
     {
         let f = std::fs::File::create(path)?;
-        let out = DotOutput::new(f);
-        let mut dumper: HeapGraphDumper<Pred, VM, DotOutput> = HeapGraphDumper::new(pred, tls, out);
+        let out = BufferedOutput::new(DotOutput::new(f), DEFAULT_BATCH_SIZE);
+        let mut dumper: HeapGraphDumper<Pred, VM, BufferedOutput<DotOutput>> =
+            HeapGraphDumper::new(pred, tls, out);
         dumper.visit_roots();
         Ok(())
     }
 
+    #[derive(Clone)]
     pub struct NodeId(String);
 
+    impl NodeId {
+        /// The dot-quoted id used to identify `obj` as a node, shared by
+        /// every traversal (serial dumper, parallel dumper, dominator dump)
+        /// so the encoding can't drift between them.
+        fn for_object(obj: ObjectReference) -> NodeId {
+            NodeId(format!("\"{:p}\"", obj.to_raw_address().to_ptr::<usize>()))
+        }
+    }
+
+    #[derive(Clone)]
     pub enum NodeAttr {
         String(String, String),
         Number(String, usize),
@@ -275,11 +545,164 @@ pub mod graph {
 
     pub trait NodeAttrs {
         fn node_attrs(obj: ObjectReference) -> Vec<NodeAttr>;
+
+        /// The object's size in bytes, used to compute retained sizes in
+        /// [`dump_dominator_tree`].
+        fn object_size(obj: ObjectReference) -> usize;
+    }
+
+    ////////////////////////////////////////////////////
+    // Buffered output
+    ////////////////////////////////////////////////////
+
+    /// A single edge or node write, recorded so it can be replayed against
+    /// the underlying [`GraphOutput`] once a batch fills up.
+    enum Record {
+        Slot(NodeId, NodeId),
+        Node(NodeId, Vec<NodeAttr>),
+    }
+
+    impl Record {
+        fn write_to<O: GraphOutput>(&self, out: &mut O) -> Result<(), std::io::Error> {
+            match self {
+                Record::Slot(src, dst) => out.add_slot(src, dst),
+                Record::Node(node_id, attrs) => out.add_node(node_id, attrs),
+            }
+        }
+    }
+
+    enum Sink<O: GraphOutput + Send + 'static> {
+        Inline(O),
+        Threaded {
+            sender: Option<crossbeam::channel::Sender<Vec<Record>>>,
+            handle: Option<std::thread::JoinHandle<()>>,
+        },
+    }
+
+    /// A [`GraphOutput`] wrapper that accumulates edge/node records in
+    /// memory and only flushes them to the underlying writer once `batch_size`
+    /// records have piled up (or the wrapper is dropped), rather than issuing
+    /// a `write(2)` per edge or node. Without this, `dump_dot` is unusably
+    /// slow on heaps with millions of objects.
+    ///
+    /// Optionally, the flushed batches can be handed off to a dedicated
+    /// writer thread over a `crossbeam` channel via [`Self::new_threaded`],
+    /// so the scanning loop in [`super::HeapGraphDumper::visit_roots`] never
+    /// blocks on I/O.
+    ///
+    /// Modeled on the write-batching `IoEngine` wrapper used by the external
+    /// thin-provisioning-tools project.
+    pub struct BufferedOutput<O: GraphOutput + Send + 'static> {
+        sink: Sink<O>,
+        batch_size: usize,
+        buffer: Vec<Record>,
+    }
+
+    impl<O: GraphOutput + Send + 'static> BufferedOutput<O> {
+        /// Wrap `output`, flushing every `batch_size` records or on `Drop`.
+        pub fn new(output: O, batch_size: usize) -> Self {
+            BufferedOutput {
+                sink: Sink::Inline(output),
+                batch_size,
+                buffer: Vec::with_capacity(batch_size),
+            }
+        }
+
+        /// Like [`Self::new`], but writes batches from a dedicated background
+        /// thread fed by an unbounded `crossbeam` channel, so flushing never
+        /// blocks the caller on I/O.
+        pub fn new_threaded(mut output: O, batch_size: usize) -> Self {
+            let (sender, receiver) = crossbeam::channel::unbounded::<Vec<Record>>();
+            let handle = std::thread::Builder::new()
+                .name("heap-graph-writer".to_string())
+                .spawn(move || {
+                    for batch in receiver {
+                        for record in &batch {
+                            record
+                                .write_to(&mut output)
+                                .expect("failed to write heap graph record");
+                        }
+                    }
+                })
+                .expect("failed to spawn heap graph writer thread");
+
+            BufferedOutput {
+                sink: Sink::Threaded {
+                    sender: Some(sender),
+                    handle: Some(handle),
+                },
+                batch_size,
+                buffer: Vec::with_capacity(batch_size),
+            }
+        }
+
+        /// The number of records buffered before a flush is triggered.
+        pub fn batch_size(&self) -> usize {
+            self.batch_size
+        }
+
+        fn push(&mut self, record: Record) -> Result<(), std::io::Error> {
+            self.buffer.push(record);
+            if self.buffer.len() >= self.batch_size {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), std::io::Error> {
+            if self.buffer.is_empty() {
+                return Ok(());
+            }
+            let batch = std::mem::replace(&mut self.buffer, Vec::with_capacity(self.batch_size));
+            match &mut self.sink {
+                Sink::Inline(out) => {
+                    for record in &batch {
+                        record.write_to(out)?;
+                    }
+                }
+                Sink::Threaded { sender, .. } => {
+                    sender
+                        .as_ref()
+                        .expect("writer thread sender already closed")
+                        .send(batch)
+                        .expect("heap graph writer thread died");
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl<O: GraphOutput + Send + 'static> GraphOutput for BufferedOutput<O> {
+        fn add_slot(&mut self, src: &NodeId, dst: &NodeId) -> Result<(), std::io::Error> {
+            self.push(Record::Slot(src.clone(), dst.clone()))
+        }
+
+        fn add_node(
+            &mut self,
+            node_id: &NodeId,
+            attrs: &Vec<NodeAttr>,
+        ) -> Result<(), std::io::Error> {
+            self.push(Record::Node(node_id.clone(), attrs.clone()))
+        }
+    }
+
+    impl<O: GraphOutput + Send + 'static> Drop for BufferedOutput<O> {
+        fn drop(&mut self) {
+            let _ = self.flush();
+            if let Sink::Threaded { sender, handle } = &mut self.sink {
+                // Dropping the sender closes the channel, letting the writer
+                // thread drain whatever is left in the queue and exit.
+                sender.take();
+                if let Some(handle) = handle.take() {
+                    let _ = handle.join();
+                }
+            }
+        }
     }
 
     struct RootSet<VM: VMBinding> {
         work_list: Arc<SegQueue<ObjectReference>>,
-        _phantom: PhantomData<VM>,
+        _phantom: PhantomData<fn() -> VM>,
     }
 
     impl<VM: VMBinding> Clone for RootSet<VM> {
@@ -386,7 +809,7 @@ pub mod graph {
         }
 
         pub fn to_node_id(&self, obj: ObjectReference) -> NodeId {
-            NodeId(format!("\"{:p}\"", obj.to_raw_address().to_ptr::<usize>()))
+            NodeId::for_object(obj)
         }
 
         pub fn visit_roots(&mut self) {
@@ -438,4 +861,515 @@ pub mod graph {
             }
         }
     }
+
+    ////////////////////////////////////////////////////
+    // Dominator tree / retained-size analysis
+    ////////////////////////////////////////////////////
+
+    /// A node in the heap graph used for dominator analysis: either a real
+    /// heap object, or the synthetic root that points at every GC root.
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+    enum GraphNode {
+        Root,
+        Object(ObjectReference),
+    }
+
+    /// Builds the full heap graph in memory, rooted at a synthetic node that
+    /// points at every GC root. Unlike [`HeapGraphDumper`], which streams
+    /// edges straight out to a [`GraphOutput`] as it scans, this keeps the
+    /// whole graph around because dominator analysis needs random access to
+    /// each node's predecessors.
+    struct GraphBuilder<Pred, VM>
+    where
+        Pred: Send + ValidityPredicate<VM> + Clone,
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+    {
+        tls: VMWorkerThread,
+        pred: Pred,
+        visited: HashSet<ObjectReference>,
+        work_list: SegQueue<ObjectReference>,
+        current_source: GraphNode,
+        successors: HashMap<GraphNode, Vec<GraphNode>>,
+        _phantom: PhantomData<fn() -> VM>,
+    }
+
+    impl<Pred, VM> SlotVisitor<<VM as VMBinding>::VMSlot> for GraphBuilder<Pred, VM>
+    where
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+        Pred: ValidityPredicate<VM> + Send + Clone,
+    {
+        fn visit_slot(&mut self, slot: <VM as VMBinding>::VMSlot) {
+            let dst = slot.load().expect("invalid");
+            self.successors
+                .entry(self.current_source)
+                .or_default()
+                .push(GraphNode::Object(dst));
+            self.work_list.push(dst);
+        }
+    }
+
+    impl<Pred, VM> GraphBuilder<Pred, VM>
+    where
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+        Pred: ValidityPredicate<VM> + Send + Clone,
+    {
+        fn new(pred: Pred, tls: VMWorkerThread) -> Self {
+            GraphBuilder {
+                tls,
+                pred,
+                visited: HashSet::new(),
+                work_list: SegQueue::new(),
+                current_source: GraphNode::Root,
+                successors: HashMap::new(),
+                _phantom: PhantomData,
+            }
+        }
+
+        /// Scan the whole heap graph reachable from the synthetic root.
+        fn build(&mut self) {
+            let root_set: RootSet<VM> = RootSet::new();
+            <VM::VMScanning as Scanning<VM>>::scan_vm_specific_roots(self.tls, root_set.clone());
+
+            while let Some(root) = root_set.work_list.pop() {
+                self.successors
+                    .entry(GraphNode::Root)
+                    .or_default()
+                    .push(GraphNode::Object(root));
+                self.work_list.push(root);
+            }
+
+            while let Some(node) = self.work_list.pop() {
+                self.visit_node(node);
+            }
+        }
+
+        fn visit_node(&mut self, node: ObjectReference) {
+            if self.visited.insert(node) {
+                self.current_source = GraphNode::Object(node);
+                if self.pred.is_valid_node(node).is_ok() {
+                    <VM as ConservativeScanning>::conservatively_scan_object(self.tls, node, self);
+                }
+            }
+        }
+    }
+
+    /// Immediate-dominator and retained-size analysis over a heap graph,
+    /// computed with the iterative Cooper-Harvey-Kennedy algorithm.
+    struct DominatorAnalysis {
+        /// Every reachable node in reverse postorder from the synthetic
+        /// root; `order[0]` is always [`GraphNode::Root`]. Nodes that are
+        /// unreachable from the root never appear here.
+        order: Vec<GraphNode>,
+        /// `idom[i]` is the index into `order` of the immediate dominator of
+        /// `order[i]`. `idom[0] == 0`: the root dominates itself.
+        idom: Vec<usize>,
+    }
+
+    impl DominatorAnalysis {
+        fn new(successors: &HashMap<GraphNode, Vec<GraphNode>>) -> Self {
+            let order = Self::reverse_postorder(successors);
+            let rpo_number: HashMap<GraphNode, usize> =
+                order.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+
+            let mut predecessors: HashMap<GraphNode, Vec<GraphNode>> = HashMap::new();
+            for (src, dsts) in successors.iter() {
+                // Only edges out of reachable nodes matter for the RPO we
+                // computed above.
+                if !rpo_number.contains_key(src) {
+                    continue;
+                }
+                for dst in dsts {
+                    predecessors.entry(*dst).or_default().push(*src);
+                }
+            }
+
+            // idom[0] (the root) is set up front; every other entry starts
+            // undefined until a processed predecessor assigns it.
+            let mut idom = vec![usize::MAX; order.len()];
+            idom[0] = 0;
+
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for b_index in 1..order.len() {
+                    let preds = match predecessors.get(&order[b_index]) {
+                        Some(preds) => preds,
+                        None => continue,
+                    };
+
+                    let mut new_idom = None;
+                    for p in preds {
+                        let p_index = match rpo_number.get(p) {
+                            Some(i) => *i,
+                            None => continue,
+                        };
+                        if idom[p_index] == usize::MAX {
+                            // Not processed yet in this pass; skip it.
+                            continue;
+                        }
+                        new_idom = Some(match new_idom {
+                            None => p_index,
+                            Some(current) => Self::intersect(&idom, current, p_index),
+                        });
+                    }
+
+                    if let Some(new_idom) = new_idom {
+                        if idom[b_index] != new_idom {
+                            idom[b_index] = new_idom;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+
+            DominatorAnalysis { order, idom }
+        }
+
+        /// Reverse postorder of the graph reachable from [`GraphNode::Root`],
+        /// via an explicit stack so a long object chain can't blow it.
+        fn reverse_postorder(successors: &HashMap<GraphNode, Vec<GraphNode>>) -> Vec<GraphNode> {
+            let mut postorder = Vec::new();
+            let mut visited = HashSet::new();
+            let mut stack: Vec<(GraphNode, usize)> = vec![(GraphNode::Root, 0)];
+            visited.insert(GraphNode::Root);
+
+            while let Some((node, child_index)) = stack.pop() {
+                let next_child = successors.get(&node).and_then(|c| c.get(child_index));
+                match next_child {
+                    Some(child) => {
+                        stack.push((node, child_index + 1));
+                        if visited.insert(*child) {
+                            stack.push((*child, 0));
+                        }
+                    }
+                    None => postorder.push(node),
+                }
+            }
+
+            postorder.reverse();
+            postorder
+        }
+
+        /// Walk two fingers up the `idom` chain, stepping whichever one has
+        /// the higher RPO number, until they coincide.
+        fn intersect(idom: &[usize], mut a: usize, mut b: usize) -> usize {
+            while a != b {
+                while a > b {
+                    a = idom[a];
+                }
+                while b > a {
+                    b = idom[b];
+                }
+            }
+            a
+        }
+
+        /// Dominator-tree children of each node, indexed by RPO number.
+        fn children(&self) -> Vec<Vec<usize>> {
+            let mut children = vec![Vec::new(); self.order.len()];
+            for i in 1..self.order.len() {
+                children[self.idom[i]].push(i);
+            }
+            children
+        }
+
+        /// Retained size of every node, indexed by RPO number: the node's
+        /// own size plus the retained size of everything it immediately
+        /// dominates. A post-order walk of the dominator tree guarantees
+        /// every child finalizes its retained size before its parent needs
+        /// it; like [`Self::reverse_postorder`], the walk uses an explicit
+        /// stack so a long dominator chain (e.g. a linked list) can't blow
+        /// the native stack.
+        fn retained_sizes(&self, own_size: impl Fn(GraphNode) -> usize) -> Vec<usize> {
+            let children = self.children();
+            let mut retained = vec![0usize; self.order.len()];
+
+            // Two-phase stack: push every node once with `child_index == 0`;
+            // each pop either descends into the next not-yet-visited child,
+            // or (once all children are done) finalizes the node's own
+            // retained size and pops back to its parent.
+            let mut stack: Vec<(usize, usize)> = vec![(0, 0)];
+            while let Some((i, child_index)) = stack.pop() {
+                match children[i].get(child_index) {
+                    Some(&c) => {
+                        stack.push((i, child_index + 1));
+                        stack.push((c, 0));
+                    }
+                    None => {
+                        let mut total = own_size(self.order[i]);
+                        for &c in &children[i] {
+                            total += retained[c];
+                        }
+                        retained[i] = total;
+                    }
+                }
+            }
+
+            retained
+        }
+    }
+
+    fn node_id_of(node: GraphNode) -> NodeId {
+        match node {
+            GraphNode::Root => NodeId(String::from("roots")),
+            GraphNode::Object(obj) => NodeId::for_object(obj),
+        }
+    }
+
+    /// Dump the heap graph's dominator tree to `path`: each node is
+    /// annotated with its retained size (the total size of every object
+    /// that would become unreachable if that node were freed) as a
+    /// `NodeAttr::Number`, and each edge is a dominator-tree edge from a
+    /// node to the node it immediately dominates.
+    ///
+    /// The graph is built over a synthetic root pointing at every GC root,
+    /// and immediate dominators are computed with the iterative
+    /// Cooper-Harvey-Kennedy algorithm: number nodes in reverse postorder,
+    /// then repeatedly recompute each node's dominator as the intersection
+    /// of its predecessors' dominators until the result stops changing.
+    pub fn dump_dominator_tree<Pred, VM>(
+        pred: Pred,
+        tls: VMWorkerThread,
+        path: &std::path::Path,
+    ) -> Result<(), std::io::Error>
+    where
+        Pred: Send + ValidityPredicate<VM> + Clone,
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+    {
+        let mut builder: GraphBuilder<Pred, VM> = GraphBuilder::new(pred, tls);
+        builder.build();
+        let analysis = DominatorAnalysis::new(&builder.successors);
+
+        // `successors` (and therefore `analysis.order`) can contain objects
+        // that failed `is_valid_node`: `GraphBuilder::visit_slot` records a
+        // destination before `visit_node` gets a chance to validate it.
+        // Reading attrs/size off such an object the same way `visit_node`
+        // does for a bad node would dereference a possibly-corrupt header.
+        let retained = analysis.retained_sizes(|node| match node {
+            GraphNode::Root => 0,
+            GraphNode::Object(obj) => match builder.pred.is_valid_node(obj) {
+                Ok(()) => <VM as NodeAttrs>::object_size(obj),
+                Err(_) => 0,
+            },
+        });
+
+        let f = std::fs::File::create(path)?;
+        let mut out = BufferedOutput::new(DotOutput::new(f), DEFAULT_BATCH_SIZE);
+        for (i, node) in analysis.order.iter().enumerate() {
+            let id = node_id_of(*node);
+            let mut attrs = match node {
+                GraphNode::Root => Vec::new(),
+                GraphNode::Object(obj) => match builder.pred.is_valid_node(*obj) {
+                    Ok(()) => <VM as NodeAttrs>::node_attrs(*obj),
+                    Err(error) => vec![NodeAttr::String("error".to_string(), error.to_string())],
+                },
+            };
+            attrs.push(NodeAttr::Number("retained_size".to_string(), retained[i]));
+            out.add_node(&id, &attrs)?;
+
+            if i != 0 {
+                let dominator_id = node_id_of(analysis.order[analysis.idom[i]]);
+                out.add_slot(&dominator_id, &id)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    ////////////////////////////////////////////////////
+    // Parallel heap graph dump
+    ////////////////////////////////////////////////////
+
+    /// A single worker in a [`dump_dot_parallel`] traversal: several of
+    /// these run concurrently, popping from the same `work_list`, scanning,
+    /// and pushing discovered nodes back. The underlying `output` is shared
+    /// and locked only for the brief push into its buffer, so the actual
+    /// file I/O (handled by `BufferedOutput`'s writer thread, if used) never
+    /// blocks a worker.
+    struct ParallelWorker<Pred, VM, O>
+    where
+        Pred: Send + ValidityPredicate<VM> + Clone,
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+        O: GraphOutput + Send + 'static,
+    {
+        tls: VMWorkerThread,
+        pred: Pred,
+        work_list: Arc<SegQueue<ObjectReference>>,
+        in_flight: Arc<AtomicUsize>,
+        visited: Arc<ShardedSet>,
+        output: Arc<Mutex<BufferedOutput<O>>>,
+        current_source: NodeId,
+        _phantom: PhantomData<fn() -> VM>,
+    }
+
+    impl<Pred, VM, O> Clone for ParallelWorker<Pred, VM, O>
+    where
+        Pred: Send + ValidityPredicate<VM> + Clone,
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+        O: GraphOutput + Send + 'static,
+    {
+        fn clone(&self) -> Self {
+            ParallelWorker {
+                tls: self.tls,
+                pred: self.pred.clone(),
+                work_list: self.work_list.clone(),
+                in_flight: self.in_flight.clone(),
+                visited: self.visited.clone(),
+                output: self.output.clone(),
+                current_source: self.current_source.clone(),
+                _phantom: PhantomData,
+            }
+        }
+    }
+
+    impl<Pred, VM, O> SlotVisitor<<VM as VMBinding>::VMSlot> for ParallelWorker<Pred, VM, O>
+    where
+        Pred: Send + ValidityPredicate<VM> + Clone,
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+        O: GraphOutput + Send + 'static,
+    {
+        fn visit_slot(&mut self, slot: <VM as VMBinding>::VMSlot) {
+            let dst = slot.load().expect("invalid");
+            let dst_id = to_node_id(dst);
+            self.output
+                .lock()
+                .expect("output lock poisoned")
+                .add_slot(&self.current_source, &dst_id)
+                .unwrap();
+            self.in_flight.fetch_add(1, Ordering::SeqCst);
+            self.work_list.push(dst);
+        }
+    }
+
+    impl<Pred, VM, O> ParallelWorker<Pred, VM, O>
+    where
+        Pred: Send + ValidityPredicate<VM> + Clone,
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+        O: GraphOutput + Send + 'static,
+    {
+        fn process_node(&mut self, node: ObjectReference) {
+            if self.visited.insert(node) {
+                let node_id = to_node_id(node);
+                self.current_source = node_id.clone();
+                match self.pred.is_valid_node(node) {
+                    Ok(()) => {
+                        let attrs = <VM as NodeAttrs>::node_attrs(node);
+                        self.output
+                            .lock()
+                            .expect("output lock poisoned")
+                            .add_node(&node_id, &attrs)
+                            .unwrap();
+                        let mut ev = self.clone();
+                        <VM as ConservativeScanning>::conservatively_scan_object(
+                            self.tls, node, &mut ev,
+                        );
+                    }
+                    Err(error) => {
+                        let attrs =
+                            vec![NodeAttr::String("error".to_string(), error.to_string())];
+                        self.output
+                            .lock()
+                            .expect("output lock poisoned")
+                            .add_node(&node_id, &attrs)
+                            .unwrap();
+                    }
+                }
+            }
+        }
+
+        fn run(mut self) {
+            struct InFlightGuard(Arc<AtomicUsize>);
+            impl Drop for InFlightGuard {
+                fn drop(&mut self) {
+                    self.0.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+
+            let backoff = crossbeam::utils::Backoff::new();
+            loop {
+                match self.work_list.pop() {
+                    Some(node) => {
+                        backoff.reset();
+                        // Release this unit of `in_flight` only after
+                        // `process_node` returns, so no other worker sees
+                        // zero while this one may still push more nodes. The
+                        // drop guard also releases it if `process_node`
+                        // panics (e.g. a poisoned output lock), so the other
+                        // workers never spin in the `None` branch forever.
+                        let _done = InFlightGuard(self.in_flight.clone());
+                        self.process_node(node);
+                    }
+                    None => {
+                        if self.in_flight.load(Ordering::SeqCst) == 0 {
+                            break;
+                        }
+                        backoff.snooze();
+                    }
+                }
+            }
+        }
+    }
+
+    fn to_node_id(obj: ObjectReference) -> NodeId {
+        NodeId::for_object(obj)
+    }
+
+    /// Like [`dump_dot`], but scans the heap graph with `num_workers`
+    /// threads draining the shared work list concurrently, following the
+    /// `SyncIoEngine`-vs-`AsyncIoEngine` split from external
+    /// thin-provisioning-tools. A [`ShardedSet`] keeps visitation idempotent
+    /// without serializing the scan behind one lock, and the shared
+    /// `in_flight` counter is used to agree on when the graph has been fully
+    /// explored. Single-threaded [`dump_dot`] remains the default.
+    pub fn dump_dot_parallel<Pred, VM>(
+        pred: Pred,
+        tls: VMWorkerThread,
+        path: &std::path::Path,
+        num_workers: usize,
+    ) -> Result<(), std::io::Error>
+    where
+        Pred: Send + ValidityPredicate<VM> + Clone + 'static,
+        VM: VMBinding + ConservativeScanning + NodeAttrs,
+    {
+        let f = std::fs::File::create(path)?;
+        let output = Arc::new(Mutex::new(BufferedOutput::new_threaded(
+            DotOutput::new(f),
+            DEFAULT_BATCH_SIZE,
+        )));
+
+        let work_list = Arc::new(SegQueue::new());
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let visited = Arc::new(ShardedSet::new());
+
+        let root_set: RootSet<VM> = RootSet::new();
+        <VM::VMScanning as Scanning<VM>>::scan_vm_specific_roots(tls, root_set.clone());
+        while let Some(root) = root_set.work_list.pop() {
+            let node_id = to_node_id(root);
+            output
+                .lock()
+                .expect("output lock poisoned")
+                .add_slot(&NodeId(String::from("roots")), &node_id)
+                .unwrap();
+            in_flight.fetch_add(1, Ordering::SeqCst);
+            work_list.push(root);
+        }
+
+        let pool = threadpool::ThreadPool::new(num_workers.max(1));
+        for _ in 0..num_workers.max(1) {
+            let worker: ParallelWorker<Pred, VM, DotOutput> = ParallelWorker {
+                tls,
+                pred: pred.clone(),
+                work_list: work_list.clone(),
+                in_flight: in_flight.clone(),
+                visited: visited.clone(),
+                output: output.clone(),
+                current_source: NodeId(String::from("unknown")),
+                _phantom: PhantomData,
+            };
+            pool.execute(move || worker.run());
+        }
+        pool.join();
+
+        Ok(())
+    }
 }